@@ -0,0 +1,87 @@
+use glam::Vec2;
+
+// maximum number of decal vertices flushed to the GPU per frame:
+// enough for ~170 quads (6 vertices each) before the buffer needs growing
+pub const MAX_DECAL_VERTICES: u64 = 1024;
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DecalVertex {
+    position: [f32; 3],
+    // third component is `q`, the projective divisor used for perspective-
+    // correct sampling; set to 1.0 for an unwarped decal
+    tex_coords: [f32; 3],
+    tint: [f32; 4],
+}
+
+impl DecalVertex {
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<DecalVertex>()
+                as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress
+                        * 2,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+// quad corners are wound TL, BL, BR, TR; UVs follow the same winding
+const QUAD_UVS: [Vec2; 4] = [
+    Vec2 { x: 0., y: 0. },
+    Vec2 { x: 0., y: 1. },
+    Vec2 { x: 1., y: 1. },
+    Vec2 { x: 1., y: 0. },
+];
+
+// derives the per-corner projective divisor `q` from where the quad's
+// diagonals intersect: the olc::PixelGameEngine "warped decal" technique
+fn quad_q(positions: &[Vec2; 4]) -> [f32; 4] {
+    let rd = (positions[2].x - positions[0].x) * (positions[3].y - positions[1].y)
+        - (positions[3].x - positions[1].x) * (positions[2].y - positions[0].y);
+    if rd == 0.0 {
+        return [1.0; 4];
+    }
+    let rd = 1.0 / rd;
+    let rn = ((positions[3].x - positions[1].x) * (positions[0].y - positions[1].y)
+        - (positions[3].y - positions[1].y) * (positions[0].x - positions[1].x))
+        * rd;
+    let center = positions[0] + rn * (positions[2] - positions[0]);
+    let d: [f32; 4] = std::array::from_fn(|i| (positions[i] - center).length());
+    [
+        (d[0] + d[2]) / d[2],
+        (d[1] + d[3]) / d[3],
+        (d[2] + d[0]) / d[0],
+        (d[3] + d[1]) / d[1],
+    ]
+}
+
+// builds the two triangles (6 vertices) for a warped, tinted decal quad
+pub fn build_decal_quad(positions: [Vec2; 4], tint: [f32; 4]) -> [DecalVertex; 6] {
+    let q = quad_q(&positions);
+    let corners: [DecalVertex; 4] = std::array::from_fn(|i| DecalVertex {
+        position: [positions[i].x, positions[i].y, 0.],
+        tex_coords: [QUAD_UVS[i].x * q[i], QUAD_UVS[i].y * q[i], q[i]],
+        tint,
+    });
+    [
+        corners[0], corners[1], corners[2],
+        corners[0], corners[2], corners[3],
+    ]
+}
@@ -0,0 +1,334 @@
+use wgpu::util::DeviceExt;
+
+use crate::texture::Texture;
+
+// one entry of a "shader preset" the way slang/RetroArch runtimes describe
+// them: a fragment shader plus how big its output should be relative to
+// the previous pass and how to sample its input.
+pub struct PassConfig {
+    pub label: &'static str,
+    pub shader_src: &'static str,
+    // output size relative to the previous pass's output (1.0 = same size)
+    pub scale: f32,
+    pub filter: wgpu::FilterMode,
+    pub wrap: wgpu::AddressMode,
+}
+
+pub const DEFAULT_PRESET: &[PassConfig] = &[PassConfig {
+    label: "blit",
+    shader_src: include_str!("blit.wgsl"),
+    scale: 1.0,
+    filter: wgpu::FilterMode::Linear,
+    wrap: wgpu::AddressMode::ClampToEdge,
+}];
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct PassUniforms {
+    source_size: [f32; 4],
+    output_size: [f32; 4],
+    frame_count: u32,
+    _padding: [u32; 3],
+}
+
+struct Pass {
+    pipeline: wgpu::RenderPipeline,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    // the intermediate texture this pass renders into; the final pass in
+    // the chain has none and targets the swapchain view instead
+    output: Option<Texture>,
+    scale: f32,
+}
+
+pub struct FilterChain {
+    passes: Vec<Pass>,
+    frame_count: u32,
+}
+
+impl FilterChain {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        preset: &[PassConfig],
+    ) -> Self {
+        let mut passes = Vec::with_capacity(preset.len());
+        // each pass's output size depends on the previous pass's (already
+        // scaled) output, starting from the surface size
+        let mut prev_size = (config.width, config.height);
+        for (i, pass_config) in preset.iter().enumerate() {
+            let is_final = i == preset.len() - 1;
+            let pass = Self::build_pass(device, config, pass_config, prev_size, is_final);
+            if !is_final {
+                prev_size = Self::scaled_size(prev_size.0, prev_size.1, pass_config.scale);
+            }
+            passes.push(pass);
+        }
+
+        Self { passes, frame_count: 0 }
+    }
+
+    fn build_pass(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        pass_config: &PassConfig,
+        input_size: (u32, u32),
+        is_final: bool,
+    ) -> Pass {
+        let bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("filter_pass_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float {
+                                filterable: true,
+                            },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(
+                            wgpu::SamplerBindingType::Filtering,
+                        ),
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(pass_config.label),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(pass_config.label),
+            source: wgpu::ShaderSource::Wgsl(pass_config.shader_src.into()),
+        });
+
+        let pipeline =
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(pass_config.label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    // full-screen triangle: positions come from vertex_index
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+            });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: pass_config.wrap,
+            address_mode_v: pass_config.wrap,
+            address_mode_w: pass_config.wrap,
+            mag_filter: pass_config.filter,
+            min_filter: pass_config.filter,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let uniform_buffer =
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("pass_uniforms"),
+                contents: bytemuck::cast_slice(&[PassUniforms {
+                    source_size: [0.; 4],
+                    output_size: [0.; 4],
+                    frame_count: 0,
+                    _padding: [0; 3],
+                }]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        let output = if is_final {
+            None
+        } else {
+            let size = Self::scaled_size(input_size.0, input_size.1, pass_config.scale);
+            Some(Self::create_pass_texture(
+                device,
+                config.format,
+                size,
+                pass_config.label,
+            ))
+        };
+
+        Pass {
+            pipeline,
+            sampler,
+            bind_group_layout,
+            uniform_buffer,
+            output,
+            scale: pass_config.scale,
+        }
+    }
+
+    fn scaled_size(width: u32, height: u32, scale: f32) -> (u32, u32) {
+        (
+            ((width as f32 * scale).round() as u32).max(1),
+            ((height as f32 * scale).round() as u32).max(1),
+        )
+    }
+
+    fn create_pass_texture(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        (width, height): (u32, u32),
+        label: &str,
+    ) -> Texture {
+        let size =
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+        Texture { texture, view, sampler }
+    }
+
+    // recreates every intermediate texture for the new surface size; called
+    // whenever `resize()` reconfigures the surface
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        let mut prev_size = (config.width, config.height);
+        let pass_count = self.passes.len();
+        for (i, pass) in self.passes.iter_mut().enumerate() {
+            let size = Self::scaled_size(prev_size.0, prev_size.1, pass.scale);
+            if i != pass_count - 1 {
+                pass.output =
+                    Some(Self::create_pass_texture(device, config.format, size, "filter_pass"));
+            }
+            prev_size = size;
+        }
+    }
+
+    // runs the whole chain: `source_view` is the freshly rendered scene,
+    // `final_view` is the swapchain texture the last pass blits into
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        source_view: &wgpu::TextureView,
+        source_size: (u32, u32),
+        final_view: &wgpu::TextureView,
+    ) {
+        self.frame_count = self.frame_count.wrapping_add(1);
+
+        let mut input_view = source_view;
+        let mut input_size = source_size;
+        let pass_count = self.passes.len();
+
+        for (i, pass) in self.passes.iter().enumerate() {
+            let output_size = pass
+                .output
+                .as_ref()
+                .map(|_| Self::scaled_size(input_size.0, input_size.1, pass.scale))
+                .unwrap_or(source_size);
+
+            queue.write_buffer(
+                &pass.uniform_buffer,
+                0,
+                bytemuck::cast_slice(&[PassUniforms {
+                    source_size: [
+                        input_size.0 as f32,
+                        input_size.1 as f32,
+                        1.0 / input_size.0 as f32,
+                        1.0 / input_size.1 as f32,
+                    ],
+                    output_size: [
+                        output_size.0 as f32,
+                        output_size.1 as f32,
+                        1.0 / output_size.0 as f32,
+                        1.0 / output_size.1 as f32,
+                    ],
+                    frame_count: self.frame_count,
+                    _padding: [0; 3],
+                }]),
+            );
+
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("filter_pass_bind_group"),
+                layout: &pass.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(input_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&pass.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: pass.uniform_buffer.as_entire_binding(),
+                    },
+                ],
+            });
+
+            let target_view =
+                if i == pass_count - 1 { final_view } else { &pass.output.as_ref().unwrap().view };
+
+            let mut render_pass =
+                encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Filter Pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: target_view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: true,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                });
+            render_pass.set_pipeline(&pass.pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            // full-screen triangle: 3 vertices, no vertex/index buffer
+            render_pass.draw(0..3, 0..1);
+            drop(render_pass);
+
+            if let Some(output) = &pass.output {
+                input_view = &output.view;
+                input_size = output_size;
+            }
+        }
+    }
+}
@@ -5,6 +5,17 @@ use winit::{
     window::{Window, WindowBuilder},
 };
 
+mod decal;
+mod filter_chain;
+mod texture;
+use decal::DecalVertex;
+use filter_chain::FilterChain;
+use glam::Vec2;
+use texture::Texture;
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
 struct State {
     surface: wgpu::Surface,
     device: wgpu::Device,
@@ -13,7 +24,20 @@ struct State {
     size: winit::dpi::PhysicalSize<u32>,
     render_pipeline: wgpu::RenderPipeline,
     vertex_buffer: wgpu::Buffer,
-    num_vertices: u32,
+    index_buffer: wgpu::Buffer,
+    num_indices: u32,
+    depth_texture: Texture,
+    diffuse_bind_group: wgpu::BindGroup,
+    decal_pipeline: wgpu::RenderPipeline,
+    decal_vertex_buffer: wgpu::Buffer,
+    decal_vertices: Vec<DecalVertex>,
+    scene_texture: Texture,
+    filter_chain: FilterChain,
+    challenge_vertex_buffer: wgpu::Buffer,
+    challenge_index_buffer: wgpu::Buffer,
+    challenge_num_indices: u32,
+    use_alt: bool,
+    show_decal: bool,
 }
 
 #[repr(C)]
@@ -22,20 +46,41 @@ struct State {
 struct Vertex {
     pos: [f32; 3],
     color: [f32; 3],
+    tex_coords: [f32; 2],
 }
 
 const VERTICES: &[Vertex] = &[
-    Vertex { pos: [0., 0.5, 0.], color: [1., 0., 0.] },
-    Vertex { pos: [-0.5, -0.5, 0.], color: [0., 1., 0.] },
-    Vertex { pos: [0.5, -0.5, 0.], color: [0., 0., 1.] },
+    Vertex { pos: [-0.0868, 0.4924, 0.], color: [1., 0., 0.], tex_coords: [0.4132, 0.0076] },
+    Vertex { pos: [-0.4951, 0.0695, 0.], color: [0., 1., 0.], tex_coords: [0.0049, 0.4305] },
+    Vertex { pos: [-0.2188, -0.4490, 0.], color: [0., 0., 1.], tex_coords: [0.2812, 0.9490] },
+    Vertex { pos: [0.3568, -0.4490, 0.], color: [1., 1., 0.], tex_coords: [0.8568, 0.9490] },
+    Vertex { pos: [0.4431, 0.2347, 0.], color: [0., 1., 1.], tex_coords: [0.9431, 0.2653] },
+];
+
+// shared-vertex pentagon: two triangle fans sharing the centre-ish vertex 4
+const INDICES: &[u16] = &[0, 1, 4, 1, 2, 4, 2, 3, 4];
+
+// alternate geometry shown after the space bar toggles use_alt: a plain quad
+const CHALLENGE_VERTICES: &[Vertex] = &[
+    Vertex { pos: [-0.5, 0.5, 0.], color: [1., 1., 1.], tex_coords: [0., 0.] },
+    Vertex { pos: [-0.5, -0.5, 0.], color: [1., 1., 1.], tex_coords: [0., 1.] },
+    Vertex { pos: [0.5, -0.5, 0.], color: [1., 1., 1.], tex_coords: [1., 1.] },
+    Vertex { pos: [0.5, 0.5, 0.], color: [1., 1., 1.], tex_coords: [1., 0.] },
 ];
+const CHALLENGE_INDICES: &[u16] = &[0, 1, 2, 0, 2, 3];
 
 impl State {
     async fn new(window: &Window) -> Self {
         let size = window.inner_size();
 
         // instance is handle to GPU: creates Adapters & Surfaces
+        // native builds pin to Vulkan; the browser has no Vulkan backend, so
+        // wasm32 asks wgpu to pick whatever it finds (WebGPU, falling back to
+        // WebGL2 via Backends::GL)
+        #[cfg(not(target_arch = "wasm32"))]
         let instance = wgpu::Instance::new(wgpu::Backends::VULKAN);
+        #[cfg(target_arch = "wasm32")]
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
         // surface is used to draw to window; needs to implement
         // raw-window-handle, thus it is unsafe
         let surface = unsafe { instance.create_surface(window) };
@@ -79,10 +124,62 @@ impl State {
         let shader =
             device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
 
+        let diffuse_bytes = include_bytes!("../assets/happy-tree.png");
+        let diffuse_texture =
+            Texture::from_bytes(&device, &queue, diffuse_bytes, "happy-tree.png")
+                .unwrap();
+
+        let texture_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("texture_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float {
+                                filterable: true,
+                            },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(
+                            wgpu::SamplerBindingType::Filtering,
+                        ),
+                        count: None,
+                    },
+                ],
+            });
+
+        let diffuse_bind_group =
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("diffuse_bind_group"),
+                layout: &texture_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(
+                            &diffuse_texture.view,
+                        ),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(
+                            &diffuse_texture.sampler,
+                        ),
+                    },
+                ],
+            });
+
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[],
+                bind_group_layouts: &[&texture_bind_group_layout],
                 push_constant_ranges: &[],
             });
 
@@ -110,7 +207,13 @@ impl State {
                     polygon_mode: wgpu::PolygonMode::Fill,
                     conservative: false,
                 },
-                depth_stencil: None,
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: Texture::DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
                 multisample: wgpu::MultisampleState {
                     // how many samples pipeline will use
                     count: 1,
@@ -142,7 +245,93 @@ impl State {
                 usage: wgpu::BufferUsages::VERTEX,
             });
 
-        let num_vertices = VERTICES.len() as u32;
+        let index_buffer =
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Index Buffer"),
+                contents: bytemuck::cast_slice(INDICES),
+                usage: wgpu::BufferUsages::INDEX,
+            });
+
+        let num_indices = INDICES.len() as u32;
+
+        let depth_texture =
+            Texture::create_depth_texture(&device, &config, "depth_texture");
+
+        let decal_shader =
+            device.create_shader_module(wgpu::include_wgsl!("decal.wgsl"));
+
+        let decal_pipeline =
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Decal Render Pipeline"),
+                layout: Some(&render_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &decal_shader,
+                    entry_point: "vs_main",
+                    buffers: &[DecalVertex::desc()],
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    // decals may be given corners in either winding order
+                    cull_mode: None,
+                    unclipped_depth: false,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: Texture::DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    // decals are UI overlays: always draw on top of the
+                    // scene regardless of what's already in the depth buffer
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &decal_shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: config.format,
+                        // decal quads composite over the scene via alpha blending
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::COLOR,
+                    })],
+                }),
+                multiview: None,
+            });
+
+        let decal_vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Decal Vertex Buffer"),
+            size: decal::MAX_DECAL_VERTICES
+                * std::mem::size_of::<DecalVertex>() as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let scene_texture =
+            Texture::create_color_texture(&device, &config, "scene_texture");
+        let filter_chain =
+            FilterChain::new(&device, &config, filter_chain::DEFAULT_PRESET);
+
+        let challenge_vertex_buffer =
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Challenge Vertex Buffer"),
+                contents: bytemuck::cast_slice(CHALLENGE_VERTICES),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+        let challenge_index_buffer =
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Challenge Index Buffer"),
+                contents: bytemuck::cast_slice(CHALLENGE_INDICES),
+                usage: wgpu::BufferUsages::INDEX,
+            });
+        let challenge_num_indices = CHALLENGE_INDICES.len() as u32;
 
         Self {
             surface,
@@ -152,24 +341,89 @@ impl State {
             size,
             render_pipeline,
             vertex_buffer,
-            num_vertices,
+            index_buffer,
+            num_indices,
+            depth_texture,
+            diffuse_bind_group,
+            decal_pipeline,
+            decal_vertex_buffer,
+            decal_vertices: Vec::new(),
+            scene_texture,
+            filter_chain,
+            challenge_vertex_buffer,
+            challenge_index_buffer,
+            challenge_num_indices,
+            use_alt: false,
+            show_decal: false,
         }
     }
 
+    // builds a warped, tinted decal quad and queues it for the next render() flush
+    fn draw_decal(&mut self, positions: [Vec2; 4], tint: [f32; 4]) {
+        self.decal_vertices.extend(decal::build_decal_quad(positions, tint));
+    }
+
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
             self.surface.configure(&self.device, &self.config);
+            // must match the resized surface or the render pass attachment
+            // sizes will disagree
+            self.depth_texture =
+                Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
+            self.scene_texture =
+                Texture::create_color_texture(&self.device, &self.config, "scene_texture");
+            self.filter_chain.resize(&self.device, &self.config);
         }
     }
 
     fn input(&mut self, event: &WindowEvent) -> bool {
-        false
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Space),
+                        ..
+                    },
+                ..
+            } => {
+                self.use_alt = !self.use_alt;
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::D),
+                        ..
+                    },
+                ..
+            } => {
+                self.show_decal = !self.show_decal;
+                true
+            }
+            _ => false,
+        }
     }
 
-    fn update(&mut self) {}
+    fn update(&mut self) {
+        // decal_vertices is flushed every render(), so a visible decal has
+        // to be re-queued each frame for as long as it should stay on screen
+        if self.show_decal {
+            self.draw_decal(
+                [
+                    Vec2::new(-0.9, 0.9),
+                    Vec2::new(-0.9, 0.55),
+                    Vec2::new(-0.55, 0.55),
+                    Vec2::new(-0.55, 0.9),
+                ],
+                [1., 1., 1., 1.],
+            );
+        }
+    }
 
     fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
         // wait for surface to provide SurfaceTexture to render to
@@ -186,7 +440,9 @@ impl State {
             encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
+                    // render the scene offscreen; the filter chain blits it
+                    // to the swapchain view below
+                    view: &self.scene_texture.view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
@@ -198,15 +454,66 @@ impl State {
                         store: true,
                     },
                 })],
-                depth_stencil_attachment: None,
+                depth_stencil_attachment: Some(
+                    wgpu::RenderPassDepthStencilAttachment {
+                        view: &self.depth_texture.view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: true,
+                        }),
+                        stencil_ops: None,
+                    },
+                ),
             });
+        let (vertex_buffer, index_buffer, num_indices) = if self.use_alt {
+            (&self.challenge_vertex_buffer, &self.challenge_index_buffer, self.challenge_num_indices)
+        } else {
+            (&self.vertex_buffer, &self.index_buffer, self.num_indices)
+        };
+
         render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        // tells wgpu to draw something with 3 vertices & 1 instance
-        render_pass.draw(0..self.num_vertices, 0..1);
+        render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        // draws the shared-vertex mesh instead of the flat vertex list
+        render_pass.draw_indexed(0..num_indices, 0, 0..1);
+
+        if !self.decal_vertices.is_empty() {
+            // decal_vertex_buffer is a fixed-size GPU allocation; write_buffer
+            // panics if we hand it more data than that, so drop the overflow
+            // rather than crash the frame
+            if self.decal_vertices.len() as u64 > decal::MAX_DECAL_VERTICES {
+                log::warn!(
+                    "dropping {} decal vertices over the {}-vertex buffer capacity",
+                    self.decal_vertices.len() as u64 - decal::MAX_DECAL_VERTICES,
+                    decal::MAX_DECAL_VERTICES,
+                );
+                self.decal_vertices.truncate(decal::MAX_DECAL_VERTICES as usize);
+            }
+            self.queue.write_buffer(
+                &self.decal_vertex_buffer,
+                0,
+                bytemuck::cast_slice(&self.decal_vertices),
+            );
+            render_pass.set_pipeline(&self.decal_pipeline);
+            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, self.decal_vertex_buffer.slice(..));
+            render_pass.draw(0..self.decal_vertices.len() as u32, 0..1);
+        }
         // dropped as encoder.finish() until mutable borrow here is released
         drop(render_pass);
 
+        self.decal_vertices.clear();
+
+        self.filter_chain.render(
+            &self.device,
+            &self.queue,
+            &mut encoder,
+            &self.scene_texture.view,
+            (self.config.width, self.config.height),
+            &view,
+        );
+
         self.queue.submit(std::iter::once(encoder.finish()));
         output.present();
         Ok(())
@@ -234,16 +541,51 @@ impl Vertex {
                     shader_location: 1,
                     format: wgpu::VertexFormat::Float32x3,
                 },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress
+                        * 2,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
             ],
         }
     }
 }
 
+// native entry point awaits run() directly; the web entry point below
+// can't block on it (no block_on in the browser), so it spawns the future
+// onto the microtask queue instead
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(start)]
+pub fn start() {
+    wasm_bindgen_futures::spawn_local(run());
+}
+
 pub async fn run() {
+    #[cfg(not(target_arch = "wasm32"))]
     env_logger::init();
+    #[cfg(target_arch = "wasm32")]
+    {
+        std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+        console_log::init_with_level(log::Level::Warn).expect("could not init console_log");
+    }
+
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new().build(&event_loop).unwrap();
 
+    // wgpu canvas must be in the DOM before surface creation can succeed
+    #[cfg(target_arch = "wasm32")]
+    {
+        use winit::platform::web::WindowExtWebSys;
+        web_sys::window()
+            .and_then(|win| win.document())
+            .and_then(|doc| doc.body())
+            .and_then(|body| {
+                body.append_child(&web_sys::Element::from(window.canvas())).ok()
+            })
+            .expect("couldn't append canvas to document body");
+    }
+
     let mut state = State::new(&window).await;
 
     event_loop.run(move |event, _, control_flow| match event {